@@ -1,5 +1,6 @@
 use byteorder::{ByteOrder, LittleEndian};
 use num_derive::FromPrimitive;
+use serde_derive::{Deserialize, Serialize};
 use solana_sdk::{
     account_info::next_account_info,
     account_info::AccountInfo,
@@ -9,15 +10,19 @@ use solana_sdk::{
     entrypoint_deprecated,
     entrypoint_deprecated::ProgramResult,
     info,
+    program::invoke_signed,
     program_error::ProgramError,
     program_pack::{Pack, Sealed},
     pubkey::Pubkey,
     rent::Rent,
+    system_instruction, system_program,
     sysvar::{self, Sysvar},
     hash::hash,
 };
 use thiserror::Error;
 
+pub mod parse_name_server;
+
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum VoteError {
     #[error("Unexpected Candidate")]
@@ -30,6 +35,8 @@ pub enum VoteError {
     AccountNotCheckAccount,
     #[error("Already Voted")]
     AlreadyVoted,
+    #[error("Invalid Instruction")]
+    InvalidInstruction,
 }
 impl From<VoteError> for ProgramError {
     fn from(e: VoteError) -> Self {
@@ -133,6 +140,55 @@ impl Pack for ServerData {
     }
 }
 
+// What a name resolves to, analogous to a DNS record. Kept in its own PDA,
+// separate from Metadata, so "who owns the name" and "what the name points
+// to" can be updated independently. `length` prefixes the meaningful bytes
+// of the fixed-capacity `data` buffer.
+pub const NAME_RECORD_DATA_LEN: usize = 256;
+
+pub struct NameRecord {
+    pub length: u32,
+    pub data: [u8; NAME_RECORD_DATA_LEN],
+}
+
+impl Sealed for NameRecord {}
+
+impl Pack for NameRecord {
+    const LEN: usize = 4 + NAME_RECORD_DATA_LEN;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let length = LittleEndian::read_u32(&src[0..4]);
+        let mut data = [0u8; NAME_RECORD_DATA_LEN];
+        data.copy_from_slice(&src[4..4 + NAME_RECORD_DATA_LEN]);
+        Ok(NameRecord { length, data })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        LittleEndian::write_u32(&mut dst[0..4], self.length);
+        dst[4..4 + NAME_RECORD_DATA_LEN].copy_from_slice(&self.data);
+    }
+}
+
+// Instructions supported by the name server program, tagged and dispatched the
+// same way `VoteInstruction` is in the vote program: bincode-serialize the
+// enum, with the variant discriminant as the leading bytes, and deserialize it
+// back at the top of `process_instruction`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum NameServerInstruction {
+    /// Claim a name, hashing it to derive the metadata PDA.
+    Register { name: String },
+
+    /// Reassign an already-claimed name to a new owner.
+    Transfer { new_owner: Pubkey },
+
+    /// Update the value a name resolves to. The owner recorded in the name's
+    /// Metadata must sign.
+    Update { name: String, value: Vec<u8> },
+
+    /// Release a claimed name so it can be registered again.
+    Unregister { name: String },
+}
+
 // Declare and export the program's entrypoint
 entrypoint_deprecated!(process_instruction);
 
@@ -140,12 +196,31 @@ entrypoint_deprecated!(process_instruction);
 fn process_instruction(
     program_id: &Pubkey,      // Public key of program account
     accounts: &[AccountInfo], // data accounts
-    instruction_data: &[u8],  // string to use for indexed name
+    instruction_data: &[u8],  // bincode-serialized NameServerInstruction
 ) -> ProgramResult {
     info!("Rust program entrypoint");
 
+    let instruction = bincode::deserialize::<NameServerInstruction>(instruction_data)
+        .map_err(|_| ProgramError::from(VoteError::InvalidInstruction))?;
+
+    match instruction {
+        NameServerInstruction::Register { name } => process_register(program_id, accounts, &name),
+        NameServerInstruction::Transfer { new_owner } => {
+            process_transfer(program_id, accounts, new_owner)
+        }
+        NameServerInstruction::Update { name, value } => {
+            process_update(program_id, accounts, &name, value)
+        }
+        NameServerInstruction::Unregister { name } => process_unregister(program_id, accounts, &name),
+    }
+}
+
+// Claims a name by hashing it to a PDA and recording the claim in the
+// program's metadata and server-wide count accounts. This is the program's
+// original (and, until now, only) behavior.
+fn process_register(program_id: &Pubkey, accounts: &[AccountInfo], name: &str) -> ProgramResult {
     // Compute the name hash from the input.
-    let name_hash = hash(&instruction_data);
+    let name_hash = hash(name.as_bytes());
 
     // Iterating accounts is safer then indexing
     let accounts_iter = &mut accounts.iter();
@@ -159,15 +234,11 @@ fn process_instruction(
         return Err(VoteError::IncorrectOwner.into());
     }
 
-    // Get the account that checks for existing mapping
+    // Get the account that checks for existing mapping. It may not exist on
+    // chain yet; if not, we create and fund it below instead of requiring the
+    // program to already own it.
     let metadata_account = next_account_info(accounts_iter)?;
 
-    // The metadata account must be owned by the program in order to modify its data
-    if metadata_account.owner != program_id {
-        info!("Metadata account not owned by program");
-        return Err(VoteError::IncorrectOwner.into());
-    }
-
     // The account must be rent exempt, i.e. live forever
     let sysvar_account = next_account_info(accounts_iter)?;
     let rent = &Rent::from_account_info(sysvar_account)?;
@@ -175,9 +246,29 @@ fn process_instruction(
         info!("Rent system account is not rent system account");
         return Err(ProgramError::InvalidAccountData);
     }
-    if !rent.is_exempt(metadata_account.lamports(), metadata_account.data_len()) {
-        info!("Check account is not rent exempt");
-        return Err(VoteError::AccountNotRentExempt.into());
+
+    // Funds and allocates the metadata account when it doesn't exist yet
+    let payer_account = next_account_info(accounts_iter)?;
+    if !payer_account.is_signer {
+        info!("Payer account is not signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let system_program_account = next_account_info(accounts_iter)?;
+    if !system_program::check_id(system_program_account.key) {
+        info!("System program account is not the system program");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (hash_pubkey, hash_bump) = Pubkey::find_program_address(&[name_hash.as_ref()], program_id);
+
+    // create_account_with_seed requires an AccountInfo for the PDA it uses as
+    // the "base" of the derived address, since that base is a required
+    // (program-)signer on the CPI and isn't otherwise one of our accounts.
+    let base_account = next_account_info(accounts_iter)?;
+    if *base_account.key != hash_pubkey {
+        info!("Base account does not match the name's derived PDA");
+        return Err(VoteError::AccountNotCheckAccount.into());
     }
 
     // the name target
@@ -188,7 +279,6 @@ fn process_instruction(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let hash_pubkey = Pubkey::new(&name_hash.as_ref());
     let expected_metadata_account_pubkey =
         Pubkey::create_with_seed(&hash_pubkey, "metadata", program_id)?;
 
@@ -197,6 +287,40 @@ fn process_instruction(
         return Err(VoteError::AccountNotCheckAccount.into());
     }
 
+    // The metadata account doesn't exist on-chain yet: create and fund it
+    // ourselves instead of requiring the caller to have already done so.
+    if metadata_account.data_is_empty() {
+        let lamports = rent.minimum_balance(Metadata::LEN);
+        let create_metadata_account_ix = system_instruction::create_account_with_seed(
+            payer_account.key,
+            metadata_account.key,
+            &hash_pubkey,
+            "metadata",
+            lamports,
+            Metadata::LEN as u64,
+            program_id,
+        );
+        invoke_signed(
+            &create_metadata_account_ix,
+            &[
+                payer_account.clone(),
+                metadata_account.clone(),
+                base_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[name_hash.as_ref(), &[hash_bump]]],
+        )?;
+    } else {
+        if metadata_account.owner != program_id {
+            info!("Metadata account not owned by program");
+            return Err(VoteError::IncorrectOwner.into());
+        }
+        if !rent.is_exempt(metadata_account.lamports(), metadata_account.data_len()) {
+            info!("Check account is not rent exempt");
+            return Err(VoteError::AccountNotRentExempt.into());
+        }
+    }
+
     let mut check_data = metadata_account.try_borrow_mut_data()?;
 
     // this unpack reads and deserialises the account data and also checks the data is the correct length
@@ -209,6 +333,9 @@ fn process_instruction(
         return Err(VoteError::AlreadyVoted.into());
     }
 
+    // Record the claiming account as the name's owner
+    metadata_check.acct_id = *target_account.key;
+
     // Increment count of names, and record the metadata
 
     let mut raw_server_data = server_account.try_borrow_mut_data()?;
@@ -224,6 +351,248 @@ fn process_instruction(
     Ok(())
 }
 
+// Reassigns a claimed name to a new owner. Requires the name's current owner
+// (as recorded in Metadata.acct_id) to sign the transaction.
+fn process_transfer(program_id: &Pubkey, accounts: &[AccountInfo], new_owner: Pubkey) -> ProgramResult {
+    if new_owner.as_ref() == [0; 32] {
+        info!("Cannot transfer to the zero address; use Unregister to release a name");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let accounts_iter = &mut accounts.iter();
+
+    let metadata_account = next_account_info(accounts_iter)?;
+    if metadata_account.owner != program_id {
+        info!("Metadata account not owned by program");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+    if metadata_account.data_len() != Metadata::LEN {
+        info!("Metadata account has the wrong length for Metadata");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The account claiming to be the name's current owner
+    let owner_account = next_account_info(accounts_iter)?;
+
+    let mut raw_metadata = metadata_account.try_borrow_mut_data()?;
+    let mut metadata = Metadata::unpack_unchecked(&raw_metadata).expect("Failed to read Metadata");
+
+    if metadata.acct_id != *owner_account.key {
+        info!("Owner account is not the name's recorded owner");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+    if !owner_account.is_signer {
+        info!("Owner account is not signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    metadata.acct_id = new_owner;
+    Metadata::pack(metadata, &mut raw_metadata).expect("Failed to write Metadata");
+
+    Ok(())
+}
+
+// Points a name at a new value, writing it into the name's NameRecord PDA.
+// Requires the name's owner (as recorded in Metadata.acct_id) to sign.
+fn process_update(program_id: &Pubkey, accounts: &[AccountInfo], name: &str, value: Vec<u8>) -> ProgramResult {
+    if value.len() > NAME_RECORD_DATA_LEN {
+        info!("Record value too large");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let name_hash = hash(name.as_bytes());
+    let accounts_iter = &mut accounts.iter();
+
+    let metadata_account = next_account_info(accounts_iter)?;
+    if metadata_account.owner != program_id {
+        info!("Metadata account not owned by program");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+
+    // May not exist on chain yet; created below if needed.
+    let record_account = next_account_info(accounts_iter)?;
+
+    // The account must be rent exempt, i.e. live forever
+    let sysvar_account = next_account_info(accounts_iter)?;
+    let rent = &Rent::from_account_info(sysvar_account)?;
+    if !sysvar::rent::check_id(sysvar_account.key) {
+        info!("Rent system account is not rent system account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Funds and allocates the record account when it doesn't exist yet
+    let payer_account = next_account_info(accounts_iter)?;
+    if !payer_account.is_signer {
+        info!("Payer account is not signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let system_program_account = next_account_info(accounts_iter)?;
+    if !system_program::check_id(system_program_account.key) {
+        info!("System program account is not the system program");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (hash_pubkey, hash_bump) = Pubkey::find_program_address(&[name_hash.as_ref()], program_id);
+
+    // create_account_with_seed requires an AccountInfo for the PDA it uses as
+    // the "base" of the derived address, since that base is a required
+    // (program-)signer on the CPI and isn't otherwise one of our accounts.
+    let base_account = next_account_info(accounts_iter)?;
+    if *base_account.key != hash_pubkey {
+        info!("Base account does not match the name's derived PDA");
+        return Err(VoteError::AccountNotCheckAccount.into());
+    }
+
+    // The account claiming to be the name's current owner
+    let owner_account = next_account_info(accounts_iter)?;
+
+    let expected_metadata_account_pubkey =
+        Pubkey::create_with_seed(&hash_pubkey, "metadata", program_id)?;
+    if expected_metadata_account_pubkey != *metadata_account.key {
+        info!("Naming violation! Not the correct metadata_account");
+        return Err(VoteError::AccountNotCheckAccount.into());
+    }
+
+    let expected_record_account_pubkey = Pubkey::create_with_seed(&hash_pubkey, "record", program_id)?;
+    if expected_record_account_pubkey != *record_account.key {
+        info!("Naming violation! Not the correct record_account");
+        return Err(VoteError::AccountNotCheckAccount.into());
+    }
+
+    let raw_metadata = metadata_account.try_borrow_data()?;
+    let metadata = Metadata::unpack_unchecked(&raw_metadata).expect("Failed to read Metadata");
+
+    if metadata.acct_id != *owner_account.key {
+        info!("Owner account is not the name's recorded owner");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+    if !owner_account.is_signer {
+        info!("Owner account is not signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // The record account doesn't exist on-chain yet: create and fund it
+    // ourselves, the same way process_register does for the metadata account.
+    if record_account.data_is_empty() {
+        let lamports = rent.minimum_balance(NameRecord::LEN);
+        let create_record_account_ix = system_instruction::create_account_with_seed(
+            payer_account.key,
+            record_account.key,
+            &hash_pubkey,
+            "record",
+            lamports,
+            NameRecord::LEN as u64,
+            program_id,
+        );
+        invoke_signed(
+            &create_record_account_ix,
+            &[
+                payer_account.clone(),
+                record_account.clone(),
+                base_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[name_hash.as_ref(), &[hash_bump]]],
+        )?;
+    } else if !rent.is_exempt(record_account.lamports(), record_account.data_len()) {
+        info!("Record account is not rent exempt");
+        return Err(VoteError::AccountNotRentExempt.into());
+    }
+
+    let mut data = [0u8; NAME_RECORD_DATA_LEN];
+    data[..value.len()].copy_from_slice(&value);
+
+    let record = NameRecord {
+        length: value.len() as u32,
+        data,
+    };
+
+    let mut raw_record = record_account.try_borrow_mut_data()?;
+    NameRecord::pack(record, &mut raw_record).expect("Failed to write NameRecord");
+
+    Ok(())
+}
+
+// Releases a claimed name, clearing its Metadata and decrementing the
+// server's name count so the name can be claimed again. Requires the name's
+// current owner (as recorded in Metadata.acct_id) to sign the transaction.
+fn process_unregister(program_id: &Pubkey, accounts: &[AccountInfo], name: &str) -> ProgramResult {
+    let name_hash = hash(name.as_bytes());
+    let accounts_iter = &mut accounts.iter();
+
+    let server_account = next_account_info(accounts_iter)?;
+    if server_account.owner != program_id {
+        info!("Server account not owned by program");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+
+    let metadata_account = next_account_info(accounts_iter)?;
+    if metadata_account.owner != program_id {
+        info!("Metadata account not owned by program");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+    if metadata_account.data_len() != Metadata::LEN {
+        info!("Metadata account has the wrong length for Metadata");
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let (hash_pubkey, _hash_bump) = Pubkey::find_program_address(&[name_hash.as_ref()], program_id);
+    let expected_metadata_account_pubkey =
+        Pubkey::create_with_seed(&hash_pubkey, "metadata", program_id)?;
+    if expected_metadata_account_pubkey != *metadata_account.key {
+        info!("Naming violation! Not the correct metadata_account");
+        return Err(VoteError::AccountNotCheckAccount.into());
+    }
+
+    // The account claiming to be the name's current owner
+    let owner_account = next_account_info(accounts_iter)?;
+
+    let mut raw_metadata = metadata_account.try_borrow_mut_data()?;
+    let mut metadata = Metadata::unpack_unchecked(&raw_metadata).expect("Failed to read Metadata");
+
+    if metadata.acct_id != *owner_account.key {
+        info!("Owner account is not the name's recorded owner");
+        return Err(VoteError::IncorrectOwner.into());
+    }
+    if !owner_account.is_signer {
+        info!("Owner account is not signer");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    metadata.acct_id = Pubkey::new(&[0; 32]);
+    Metadata::pack(metadata, &mut raw_metadata).expect("Failed to write Metadata");
+
+    // Clear any resolved value, so that whoever claims the name next doesn't
+    // inherit the previous owner's record.
+    let record_account = next_account_info(accounts_iter)?;
+    if record_account.owner == program_id && record_account.data_len() == NameRecord::LEN {
+        let expected_record_account_pubkey =
+            Pubkey::create_with_seed(&hash_pubkey, "record", program_id)?;
+        if expected_record_account_pubkey != *record_account.key {
+            info!("Naming violation! Not the correct record_account");
+            return Err(VoteError::AccountNotCheckAccount.into());
+        }
+
+        let mut raw_record = record_account.try_borrow_mut_data()?;
+        let empty_record = NameRecord {
+            length: 0,
+            data: [0u8; NAME_RECORD_DATA_LEN],
+        };
+        NameRecord::pack(empty_record, &mut raw_record).expect("Failed to write NameRecord");
+    }
+
+    let mut raw_server_data = server_account.try_borrow_mut_data()?;
+    let mut server_data =
+        ServerData::unpack_unchecked(&raw_server_data).expect("Failed to read ServerData");
+
+    server_data.name_count -= 1;
+
+    ServerData::pack(server_data, &mut raw_server_data).expect("Failed to write ServerData");
+
+    Ok(())
+}
+
 // Required to support info! in tests
 #[cfg(not(target_arch = "bpf"))]
 solana_sdk::program_stubs!();
@@ -0,0 +1,61 @@
+//! Decodes this program's raw account bytes into structured, JSON-friendly
+//! types, the same way `solana-account-decoder`'s `parse_vote`/`parse_config`
+//! modules turn other programs' accounts into something an RPC
+//! `getAccountInfo { encoding: "jsonParsed" }` call or an explorer can render.
+
+use crate::{Metadata, NameRecord, ServerData};
+use serde_derive::{Deserialize, Serialize};
+use solana_sdk::program_pack::Pack;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("Invalid account data length {0}")]
+    InvalidAccountData(usize),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum NameServerAccountType {
+    ServerData { name_count: u32 },
+    Metadata { owner: Option<String> },
+    NameRecord { value: String },
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes the raw data of an account owned by this program. Callers are
+/// expected to have already checked the account's owner equals `program_id`;
+/// the variant to decode into is then picked from the data length alone.
+pub fn parse_name_server(data: &[u8]) -> Result<NameServerAccountType, ParseError> {
+    match data.len() {
+        ServerData::LEN => {
+            let server_data = ServerData::unpack_from_slice(data)
+                .map_err(|_| ParseError::InvalidAccountData(data.len()))?;
+            Ok(NameServerAccountType::ServerData {
+                name_count: server_data.name_count,
+            })
+        }
+        Metadata::LEN => {
+            let metadata = Metadata::unpack_from_slice(data)
+                .map_err(|_| ParseError::InvalidAccountData(data.len()))?;
+            let owner = if metadata.acct_id.to_bytes() == [0; 32] {
+                None
+            } else {
+                Some(metadata.acct_id.to_string())
+            };
+            Ok(NameServerAccountType::Metadata { owner })
+        }
+        NameRecord::LEN => {
+            let record = NameRecord::unpack_from_slice(data)
+                .map_err(|_| ParseError::InvalidAccountData(data.len()))?;
+            let length = (record.length as usize).min(record.data.len());
+            Ok(NameServerAccountType::NameRecord {
+                value: encode_hex(&record.data[..length]),
+            })
+        }
+        _ => Err(ParseError::InvalidAccountData(data.len())),
+    }
+}